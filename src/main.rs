@@ -1,16 +1,29 @@
 pub mod modules;
 
 use modules::config;
+use modules::integrity::{self, CheckOptions};
+use modules::prune;
+use modules::store;
 use crate::modules::watcher::watcher;
+use std::env;
 
 fn main() {
+   let mut args = env::args().skip(1);
+   match args.next().as_deref() {
+      Some("check") => run_check(args),
+      Some("prune") => run_prune(args),
+      Some("restore") => run_restore(args),
+      _ => run_watch(),
+   }
+}
+
+fn run_watch() {
    let paths = config::deserialize_config();
    // dbg!(&paths);
    match paths {
       Ok(config) => {
-         let watch_paths = config::extract_paths(&config);
-         println!("{:?}", watch_paths);
-         watcher(watch_paths);
+         println!("{:?}", config::extract_paths(&config));
+         watcher(config);
       }
       Err(err) => {
          panic!("{}", err);
@@ -18,3 +31,58 @@ fn main() {
    }
 }
 
+fn run_check(args: impl Iterator<Item = String>) {
+   let mut path = None;
+   let mut repair = false;
+   for arg in args {
+      match arg.as_str() {
+         "--repair" => repair = true,
+         other => path = Some(other.to_string()),
+      }
+   }
+
+   match integrity::run_check(CheckOptions { path, repair }) {
+      Ok(report) => println!("{}", report.summary()),
+      Err(err) => panic!("{}", err),
+   }
+}
+
+fn run_restore(args: impl Iterator<Item = String>) {
+   let mut path = None;
+   let mut dest_dir = None;
+   let mut args = args.peekable();
+   while let Some(arg) = args.next() {
+      match arg.as_str() {
+         "--to" => dest_dir = args.next(),
+         other => path = Some(other.to_string()),
+      }
+   }
+
+   let Some(path) = path else {
+      panic!("usage: bacman restore <path> [--to <dir>]");
+   };
+
+   match store::restore_path(&path, dest_dir.as_deref()) {
+      Ok(count) => println!("restored {} file(s) from {}", count, path),
+      Err(err) => panic!("{}", err),
+   }
+}
+
+fn run_prune(args: impl Iterator<Item = String>) {
+   let dry_run = args.into_iter().any(|arg| arg == "--dry-run");
+
+   match prune::run_prune(dry_run) {
+      Ok(report) => {
+         let verb = if dry_run { "would remove" } else { "removed" };
+         for backup in &report.removed {
+            println!("{verb}: {} @ {}", backup.path, backup.timestamp);
+         }
+         println!(
+            "{} backups, {} chunks freed",
+            report.removed.len(),
+            report.chunks_freed
+         );
+      }
+      Err(err) => panic!("{}", err),
+   }
+}
@@ -0,0 +1,146 @@
+//! Content-defined chunking via a Gear-hash rolling window (a simplified
+//! FastCDC). Because the cut points depend only on local byte content, two
+//! files that share a run of bytes produce identical chunks regardless of
+//! insertions or deletions elsewhere, which is what lets the store
+//! deduplicate across files and across backups.
+
+/// Chunk boundaries are not considered before this many bytes have been
+/// read, bounding how small a chunk can get.
+const MIN_CHUNK_SIZE: usize = 2 * 1024;
+/// A boundary is forced at this size even if the rolling hash never hits
+/// the mask, bounding how large a chunk can get.
+const MAX_CHUNK_SIZE: usize = 64 * 1024;
+/// `hash & MASK == 0` on average every `MASK + 1` bytes, so this targets an
+/// ~8 KiB average chunk size.
+const MASK: u64 = (1 << 13) - 1;
+
+/// A content-defined slice of a file, identified by the strong hash of its
+/// bytes so identical chunks across files hash identically.
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    pub hash: String,
+    pub offset: u64,
+    pub length: u64,
+    pub data: Vec<u8>,
+}
+
+fn gear_table() -> &'static [u64; 256] {
+    static TABLE: std::sync::OnceLock<[u64; 256]> = std::sync::OnceLock::new();
+    TABLE.get_or_init(|| {
+        // Deterministic pseudo-random table (xorshift64), not meant to be
+        // cryptographic -- it only needs to scatter byte values well enough
+        // to make cut points content-dependent.
+        let mut table = [0u64; 256];
+        let mut seed: u64 = 0x9E37_79B9_7F4A_7C15;
+        for entry in table.iter_mut() {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            *entry = seed;
+        }
+        table
+    })
+}
+
+/// Splits `data` into content-defined chunks, each hashed with BLAKE3.
+pub fn chunk(data: &[u8]) -> Vec<Chunk> {
+    let table = gear_table();
+    let mut chunks = Vec::new();
+    let mut start = 0usize;
+    let mut hash: u64 = 0;
+
+    for i in 0..data.len() {
+        hash = (hash << 1).wrapping_add(table[data[i] as usize]);
+        let len = i - start + 1;
+
+        let at_boundary = len >= MIN_CHUNK_SIZE && hash & MASK == 0;
+        let forced = len >= MAX_CHUNK_SIZE;
+        let at_end = i == data.len() - 1;
+
+        if at_boundary || forced || at_end {
+            let slice = &data[start..=i];
+            chunks.push(Chunk {
+                hash: blake3::hash(slice).to_hex().to_string(),
+                offset: start as u64,
+                length: slice.len() as u64,
+                data: slice.to_vec(),
+            });
+            start = i + 1;
+            hash = 0;
+        }
+    }
+
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reassembles_to_original_bytes() {
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let chunks = chunk(&data);
+
+        let mut reassembled = Vec::with_capacity(data.len());
+        for c in &chunks {
+            reassembled.extend_from_slice(&c.data);
+        }
+        assert_eq!(reassembled, data);
+    }
+
+    #[test]
+    fn respects_min_and_max_chunk_size() {
+        let data = vec![0u8; 300_000];
+        let chunks = chunk(&data);
+
+        assert!(chunks.len() > 1);
+        for c in &chunks[..chunks.len() - 1] {
+            assert!(c.length as usize >= MIN_CHUNK_SIZE);
+            assert!(c.length as usize <= MAX_CHUNK_SIZE);
+        }
+        // The final chunk can be shorter than MIN_CHUNK_SIZE since it's cut
+        // off by the end of the data rather than a boundary or the max size.
+        assert!(chunks.last().unwrap().length as usize <= MAX_CHUNK_SIZE);
+    }
+
+    /// Deterministic pseudo-random bytes (xorshift64, same scheme as
+    /// `gear_table`) -- a short repeating byte pattern has long runs the
+    /// gear hash never cuts inside of, which would make this test pass or
+    /// fail on accident rather than on whether dedup actually works.
+    fn pseudo_random_bytes(n: usize, seed: u64) -> Vec<u8> {
+        let mut seed = seed;
+        (0..n)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                (seed & 0xFF) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn identical_content_produces_identical_chunk_hashes() {
+        let shared = pseudo_random_bytes(100_000, 0xDEAD_BEEF_CAFE_F00D);
+
+        let mut a = b"some unique prefix for file a".to_vec();
+        a.extend_from_slice(&shared);
+
+        let mut b = b"a completely different prefix".to_vec();
+        b.extend_from_slice(&shared);
+
+        let chunks_a = chunk(&a);
+        let chunks_b = chunk(&b);
+
+        let hashes_a: std::collections::HashSet<&str> =
+            chunks_a.iter().map(|c| c.hash.as_str()).collect();
+        let hashes_b: std::collections::HashSet<&str> =
+            chunks_b.iter().map(|c| c.hash.as_str()).collect();
+
+        assert!(
+            hashes_a.intersection(&hashes_b).count() > 0,
+            "expected at least one chunk shared between files with common content"
+        );
+    }
+}
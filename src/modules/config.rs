@@ -5,12 +5,32 @@ use serde::Deserialize;
 use directories::ProjectDirs;
 use std::path::Path;
 use thiserror::Error;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use crate::modules::crypto;
+use crate::modules::store::BackupManifest;
+
+/// Patterns excluded from every backup unless a path or profile sets
+/// `no_default_excludes = true`. Mirrors the spirit of zvault's
+/// `DEFAULT_EXCLUDES`: caches, VCS metadata and build output that users
+/// almost never want backed up.
+pub const DEFAULT_EXCLUDES: &[&str] = &[
+    "**/.git/",
+    "**/node_modules/",
+    "**/target/",
+    "**/.cache/",
+    "*.tmp",
+    "*.swp",
+    "**/__pycache__/",
+];
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub global: GlobalConfig,
     pub profiles: HashMap<String, Profile>,
     pub backup_paths: Vec<BackupPath>,
+    // Maps a short alias (e.g. "my-nas") to a concrete destination, so
+    // `backup_to` doesn't need to repeat long paths/URLs across entries.
+    pub destinations: Option<HashMap<String, String>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -18,12 +38,27 @@ pub struct GlobalConfig {
     pub default_profile: Option<String>,
 }
 
+/// Keep-rules for pruning old backups, bucketed the standard way: the most
+/// recent backup in each distinct day/week/month bucket survives, up to the
+/// configured count, and `keep_last` is always honored on top of that.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Retention {
+    pub keep_last: Option<usize>,
+    pub keep_daily: Option<usize>,
+    pub keep_weekly: Option<usize>,
+    pub keep_monthly: Option<usize>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Profile {
     pub encrypt: Option<bool>,
     pub backup_method: Option<Vec<String>>,
     pub backup_to: Option<String>,
     pub interval: Option<String>,
+    pub exclude: Option<Vec<String>>,
+    pub no_default_excludes: Option<bool>,
+    pub same_device: Option<bool>,
+    pub retention: Option<Retention>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -35,6 +70,10 @@ pub struct BackupPath {
     pub backup_method: Option<Vec<String>>,
     pub backup_to: Option<String>,
     pub interval: Option<String>,
+    pub exclude: Option<Vec<String>>,
+    pub no_default_excludes: Option<bool>,
+    pub same_device: Option<bool>,
+    pub retention: Option<Retention>,
 }
 
 #[derive(Debug)]
@@ -44,6 +83,17 @@ pub struct ResolvedBackupPath {
     pub backup_method: Option<Vec<String>>,
     pub backup_to: Option<String>,
     pub interval: Option<String>,
+    // Fully merged exclude patterns (path overrides profile, default
+    // excludes appended unless disabled) ready to compile into a `GlobSet`.
+    pub exclude: Vec<String>,
+    // When true, traversal must not cross onto a different filesystem
+    // than `path`'s own (the `--xdev` behavior). No-op on non-Unix targets.
+    pub same_device: bool,
+    // Slot for the previous backup's manifest of a `dedup` path. Config
+    // resolution always leaves it `None` and nothing in this crate sets it:
+    // the watcher loads its reference manifest from the store itself.
+    pub reference: Option<BackupManifest>,
+    pub retention: Retention,
 }
 
 #[derive(Error, Debug)]
@@ -53,39 +103,88 @@ pub enum ConfigError {
 }
 
 impl Config {
+    /// The profile that applies to `path`: its own `profile` reference if
+    /// set, falling back to `[global] default_profile`. Shared by
+    /// `resolve_backup_paths` and `validate` so both agree on which profile
+    /// a path's unset fields fall back to.
+    fn profile_for(&self, path: &BackupPath) -> Option<&Profile> {
+        path.profile
+            .as_ref()
+            .and_then(|p| self.profiles.get(p))
+            .or_else(|| {
+                self.global
+                    .default_profile
+                    .as_ref()
+                    .and_then(|default| self.profiles.get(default))
+            })
+    }
+
     pub fn resolve_backup_paths(&self) -> Vec<ResolvedBackupPath> {
         self.backup_paths
             .iter()
             .map(|path| {
-                // Get the profile if specified, or use default profile
-                let profile = path
-                    .profile
-                    .as_ref()
-                    .and_then(|p| self.profiles.get(p))
-                    .or_else(|| {
-                        self.global
-                            .default_profile
-                            .as_ref()
-                            .and_then(|default| self.profiles.get(default))
-                    });
+                let profile = self.profile_for(path);
 
                 // Resolve each field, prioritizing path-specific settings over profile settings
+                let no_default_excludes = path
+                    .no_default_excludes
+                    .or_else(|| profile.and_then(|p| p.no_default_excludes))
+                    .unwrap_or(false);
+
+                let mut exclude = path
+                    .exclude
+                    .clone()
+                    .or_else(|| profile.and_then(|p| p.exclude.clone()))
+                    .unwrap_or_default();
+                if !no_default_excludes {
+                    exclude.extend(DEFAULT_EXCLUDES.iter().map(|s| s.to_string()));
+                }
+
                 ResolvedBackupPath {
                     path: path.path.clone(),
                     encrypt: path.encrypt.or_else(|| profile.and_then(|p| p.encrypt)),
                     backup_method: path.backup_method.clone().or_else(|| {
                         profile.and_then(|p| p.backup_method.clone())
                     }),
-                    backup_to: path.backup_to.clone().or_else(|| {
-                        profile.and_then(|p| p.backup_to.clone())
-                    }),
+                    backup_to: path
+                        .backup_to
+                        .clone()
+                        .or_else(|| profile.and_then(|p| p.backup_to.clone()))
+                        .map(|dest| self.resolve_destination(&dest).unwrap_or(dest)),
                     interval: path.interval.clone().or_else(|| {
                         profile.and_then(|p| p.interval.clone())
                     }),
+                    exclude,
+                    same_device: path
+                        .same_device
+                        .or_else(|| profile.and_then(|p| p.same_device))
+                        .unwrap_or(false),
+                    reference: None,
+                    retention: path
+                        .retention
+                        .clone()
+                        .or_else(|| profile.and_then(|p| p.retention.clone()))
+                        .unwrap_or_default(),
                 }
             })
             .collect()
     }
+
+    /// Resolves a `backup_to` value against the `[destinations]` alias
+    /// table. Already-concrete destinations (absolute/`./` paths, `git@`,
+    /// `https://`) are returned unchanged; anything else is looked up by
+    /// name and errors if no such alias is configured.
+    fn resolve_destination(&self, dest: &str) -> Result<String, String> {
+        if is_concrete_destination(dest) {
+            return Ok(dest.to_string());
+        }
+        self.destinations
+            .as_ref()
+            .and_then(|table| table.get(dest))
+            .cloned()
+            .ok_or_else(|| format!("Unknown destination alias: {}", dest))
+    }
+
     pub fn validate(&self) -> Result<(), ConfigError> {
         let mut errors = Vec::new();
 
@@ -96,6 +195,38 @@ impl Config {
             }
         }
 
+        // Validate exclude glob syntax and retention rules on profiles
+        for (name, profile) in &self.profiles {
+            if let Some(patterns) = &profile.exclude {
+                for pattern in patterns {
+                    if let Err(e) = Glob::new(pattern) {
+                        errors.push(format!(
+                            "Invalid exclude pattern '{}' in profile '{}': {}",
+                            pattern, name, e
+                        ));
+                    }
+                }
+            }
+
+            if let Some(retention) = &profile.retention {
+                let has_rule = [
+                    retention.keep_last,
+                    retention.keep_daily,
+                    retention.keep_weekly,
+                    retention.keep_monthly,
+                ]
+                .iter()
+                .any(|rule| rule.is_some_and(|count| count > 0));
+
+                if !has_rule {
+                    errors.push(format!(
+                        "Retention table for profile '{}' must set a positive keep_last/keep_daily/keep_weekly/keep_monthly",
+                        name
+                    ));
+                }
+            }
+        }
+
         // Validate backup paths and their profiles
         for path in &self.backup_paths {
             // Check path exists
@@ -114,7 +245,7 @@ impl Config {
             if let Some(methods) = &path.backup_method {
                 for method in methods {
                     match method.to_lowercase().as_str() {
-                        "local" | "git" | "gdrive" | "pdrive" | "dropbox" => (),
+                        "local" | "git" | "gdrive" | "pdrive" | "dropbox" | "dedup" => (),
                         _ => errors.push(format!("Invalid backup method: {}", method)),
                     }
                 }
@@ -122,33 +253,81 @@ impl Config {
 
             // Validate interval format if specified
             if let Some(interval) = &path.interval {
-                if !interval.chars().any(|c| c.is_digit(10))
-                    || !interval.ends_with(|c| matches!(c, 'd' | 'h' | 'm')) {
+                if !interval.chars().any(|c| c.is_ascii_digit())
+                    || !interval.ends_with(['d', 'h', 'm']) {
                     errors.push(format!("Invalid interval format: {}", interval));
                 }
             }
 
-            // Validate backup destination if specified
-            if let Some(dest) = &path.backup_to {
-                if dest.starts_with('/') || dest.starts_with("./") {
-                    if !Path::new(dest).exists() {
-                        errors.push(format!("Backup destination not accessible: {}", dest));
+            // Validate exclude glob syntax so bad patterns fail fast at startup
+            if let Some(patterns) = &path.exclude {
+                for pattern in patterns {
+                    if let Err(e) = Glob::new(pattern) {
+                        errors.push(format!("Invalid exclude pattern '{}': {}", pattern, e));
+                    }
+                }
+            }
+
+            // Validate retention rules: a table with no positive rule is
+            // treated by prune like no retention at all (nothing is removed),
+            // so a retention table must name at least one positive rule.
+            if let Some(retention) = &path.retention {
+                let has_rule = [
+                    retention.keep_last,
+                    retention.keep_daily,
+                    retention.keep_weekly,
+                    retention.keep_monthly,
+                ]
+                .iter()
+                .any(|rule| rule.is_some_and(|count| count > 0));
+
+                if !has_rule {
+                    errors.push(format!(
+                        "Retention table for path '{}' must set a positive keep_last/keep_daily/keep_weekly/keep_monthly",
+                        path.path
+                    ));
+                }
+            }
+
+            // Validate backup destination if specified (on the path itself
+            // or inherited from its profile), resolving aliases against
+            // `[destinations]` before the format checks below
+            let profile = self.profile_for(path);
+            let merged_backup_to = path
+                .backup_to
+                .clone()
+                .or_else(|| profile.and_then(|p| p.backup_to.clone()));
+            if let Some(dest) = &merged_backup_to {
+                match self.resolve_destination(dest) {
+                    Ok(resolved) => {
+                        if resolved.starts_with('/') || resolved.starts_with("./") {
+                            if !Path::new(&resolved).exists() {
+                                errors.push(format!("Backup destination not accessible: {}", resolved));
+                            }
+                        } else if !resolved.starts_with("git@") && !resolved.starts_with("https://") {
+                            errors.push(format!("Invalid backup destination format: {}", resolved));
+                        }
                     }
-                } else if !dest.starts_with("git@") && !dest.starts_with("https://") {
-                    errors.push(format!("Invalid backup destination format: {}", dest));
+                    Err(e) => errors.push(e),
                 }
             }
         }
 
         // Validate resolved paths have required fields
         let resolved = self.resolve_backup_paths();
-        for path in resolved {
+        for path in &resolved {
             if path.backup_method.is_none() {
                 errors.push(format!("No backup method specified for path: {}", path.path));
             }
             if path.backup_to.is_none() {
                 errors.push(format!("No backup destination specified for path: {}", path.path));
             }
+
+            // No key-material check here: `deserialize_config` runs
+            // `bootstrap_encryption_keys` before calling `validate`, so by
+            // the time this loop runs, every `encrypt = true` path already
+            // has a key -- bootstrapping failing at all surfaces as an
+            // `Err` before `validate` is ever reached.
         }
 
 
@@ -173,6 +352,8 @@ pub fn deserialize_config() -> Result<Vec<ResolvedBackupPath>, String> {
                     let config = toml::from_str::<Config>(&config_str)
                         .map_err(|e| format!("Failed to parse config: {}", e))?;
 
+                    bootstrap_encryption_keys(&config).map_err(|e| e.to_string())?;
+
                     config.validate()
                         .map_err(|e| e.to_string())?;
 
@@ -181,6 +362,48 @@ pub fn deserialize_config() -> Result<Vec<ResolvedBackupPath>, String> {
         })
 }
 
+fn is_concrete_destination(dest: &str) -> bool {
+    dest.starts_with('/') || dest.starts_with("./") || dest.starts_with("git@") || dest.starts_with("https://")
+}
+
+/// Expands one user-supplied pattern into the glob(s) needed to match both
+/// the path itself and anything underneath it. A changed file's path never
+/// ends in `/`, so a directory-style pattern like `**/node_modules/` or a
+/// bare directory path like `/abs/path` would otherwise never match any
+/// file inside that directory -- only the directory entry itself.
+fn normalize_exclude_pattern(pattern: &str) -> [String; 2] {
+    let base = pattern.strip_suffix('/').unwrap_or(pattern);
+    [base.to_string(), format!("{}/**", base)]
+}
+
+/// Compiles a resolved path's exclude patterns into a single `GlobSet` so
+/// the watcher can match a candidate file in O(1) amortized time instead of
+/// re-parsing globs on every filesystem event. Invalid patterns are skipped
+/// here since `Config::validate()` already rejects them at startup.
+pub fn build_exclude_set(patterns: &[String]) -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        for expanded in normalize_exclude_pattern(pattern) {
+            if let Ok(glob) = Glob::new(&expanded) {
+                builder.add(glob);
+            }
+        }
+    }
+    builder.build().unwrap_or_else(|_| GlobSetBuilder::new().build().unwrap())
+}
+
+/// Generates a key for every resolved path that enables `encrypt` and
+/// doesn't have one yet, so a first run bootstraps key material before
+/// anything tries to encrypt or decrypt with it.
+fn bootstrap_encryption_keys(config: &Config) -> Result<(), crypto::CryptoError> {
+    for path in config.resolve_backup_paths() {
+        if path.encrypt == Some(true) {
+            crypto::ensure_key(&crypto::repo_id(&path))?;
+        }
+    }
+    Ok(())
+}
+
 pub fn extract_paths(configs: &Vec<ResolvedBackupPath>) -> Vec<String> {
     let mut paths: Vec<String> = vec![];
     for config in configs {
@@ -189,3 +412,26 @@ pub fn extract_paths(configs: &Vec<ResolvedBackupPath>) -> Vec<String> {
     }
     paths
 }
+
+#[cfg(test)]
+mod exclude_tests {
+    use super::*;
+
+    #[test]
+    fn default_excludes_match_files_inside_excluded_directories() {
+        let set = build_exclude_set(&DEFAULT_EXCLUDES.iter().map(|s| s.to_string()).collect::<Vec<_>>());
+
+        assert!(set.is_match("/home/user/project/node_modules/foo.js"));
+        assert!(set.is_match("/home/user/project/.git/HEAD"));
+        assert!(set.is_match("/home/user/project/target/debug/build"));
+    }
+
+    #[test]
+    fn absolute_path_pattern_matches_files_underneath_it() {
+        let set = build_exclude_set(&["/abs/path".to_string()]);
+
+        assert!(set.is_match("/abs/path"));
+        assert!(set.is_match("/abs/path/nested/file.txt"));
+        assert!(!set.is_match("/abs/other/file.txt"));
+    }
+}
@@ -0,0 +1,107 @@
+//! Authenticated encryption for backup artifacts headed to a destination
+//! that isn't fully trusted (gdrive, dropbox, a remote git repo, ...).
+//! Each destination ("repository") gets its own key, generated once and
+//! stored under the config directory, so encrypted destinations can be
+//! decrypted again on restore without any other side channel.
+
+use crate::modules::config::ResolvedBackupPath;
+use directories::ProjectDirs;
+use sodiumoxide::crypto::secretbox;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum CryptoError {
+    #[error("no encryption key configured for repository '{0}'; back up once to bootstrap one")]
+    MissingKey(String),
+    #[error("decryption failed: ciphertext is corrupt or the key is wrong")]
+    DecryptFailed,
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+}
+
+fn keys_dir() -> PathBuf {
+    ProjectDirs::from("", "", "bacman")
+        .map(|dirs| dirs.config_dir().join("keys"))
+        .unwrap_or_else(|| PathBuf::from("./keys"))
+}
+
+fn key_path(repo: &str) -> PathBuf {
+    keys_dir().join(format!("{}.key", repo))
+}
+
+fn sanitize(id: &str) -> String {
+    id.replace(['/', '\\', ':', '@'], "_")
+}
+
+/// The key identifier for a resolved path: the destination it backs up to
+/// when one is configured, since paths sharing a destination should share
+/// a key, falling back to the source path otherwise.
+pub fn repo_id(path: &ResolvedBackupPath) -> String {
+    sanitize(path.backup_to.as_deref().unwrap_or(&path.path))
+}
+
+pub fn has_key(repo: &str) -> bool {
+    key_path(repo).exists()
+}
+
+/// Generates and persists a new key for `repo` if one does not already
+/// exist, so the first backup to a destination bootstraps its own key.
+pub fn ensure_key(repo: &str) -> Result<(), CryptoError> {
+    let path = key_path(repo);
+    if path.exists() {
+        return Ok(());
+    }
+    let dir = keys_dir();
+    fs::create_dir_all(&dir)?;
+    harden_permissions(&dir, 0o700)?;
+    let key = secretbox::gen_key();
+    fs::write(&path, key.0)?;
+    harden_permissions(&path, 0o600)?;
+    Ok(())
+}
+
+/// Restricts `path` to `mode`, since the keys directory and the key files
+/// inside it are the only thing standing between an encrypted destination
+/// and anyone else with a shell on this machine.
+#[cfg(unix)]
+fn harden_permissions(path: &PathBuf, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn harden_permissions(_path: &PathBuf, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+fn load_key(repo: &str) -> Result<secretbox::Key, CryptoError> {
+    let bytes = fs::read(key_path(repo)).map_err(|_| CryptoError::MissingKey(repo.to_string()))?;
+    secretbox::Key::from_slice(&bytes).ok_or_else(|| CryptoError::MissingKey(repo.to_string()))
+}
+
+/// Encrypts `data` for `repo`, prepending the randomly generated nonce so
+/// `decrypt` doesn't need it passed separately.
+pub fn encrypt(repo: &str, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let key = load_key(repo)?;
+    let nonce = secretbox::gen_nonce();
+    let ciphertext = secretbox::seal(data, &nonce, &key);
+
+    let mut out = Vec::with_capacity(nonce.0.len() + ciphertext.len());
+    out.extend_from_slice(&nonce.0);
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+/// Decrypts an artifact produced by `encrypt`.
+pub fn decrypt(repo: &str, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let key = load_key(repo)?;
+    if data.len() < secretbox::NONCEBYTES {
+        return Err(CryptoError::DecryptFailed);
+    }
+    let (nonce_bytes, ciphertext) = data.split_at(secretbox::NONCEBYTES);
+    let nonce = secretbox::Nonce::from_slice(nonce_bytes).ok_or(CryptoError::DecryptFailed)?;
+    secretbox::open(ciphertext, &nonce, &key).map_err(|_| CryptoError::DecryptFailed)
+}
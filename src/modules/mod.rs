@@ -0,0 +1,7 @@
+pub mod chunker;
+pub mod config;
+pub mod crypto;
+pub mod integrity;
+pub mod prune;
+pub mod store;
+pub mod watcher;
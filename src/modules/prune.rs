@@ -0,0 +1,268 @@
+//! Retention/prune for `dedup`-backed paths, inspired by zvault's
+//! vacuum/prune options: bucket backups by day/week/month, keep the most
+//! recent one per bucket up to the configured count, and physically reclaim
+//! any chunk no surviving backup references.
+
+use crate::modules::config::{self, Retention, ResolvedBackupPath};
+use crate::modules::crypto;
+use crate::modules::store::{self, Backup, ChunkStore};
+use std::collections::HashSet;
+
+/// One backup slated for removal, reported before (`--dry-run`) or after an
+/// actual prune.
+#[derive(Debug, Clone)]
+pub struct PrunedBackup {
+    pub path: String,
+    pub timestamp: i64,
+}
+
+/// Result of a prune run: what was removed and how many chunks it freed.
+#[derive(Debug, Default)]
+pub struct PruneReport {
+    pub removed: Vec<PrunedBackup>,
+    pub chunks_freed: usize,
+}
+
+/// Runs retention pruning over every `dedup`-backed configured path. When
+/// `dry_run` is true, nothing is deleted -- the report lists what would be.
+pub fn run_prune(dry_run: bool) -> Result<PruneReport, String> {
+    let resolved = config::deserialize_config()?;
+
+    let mut report = PruneReport::default();
+    for path in resolved.iter().filter(|p| is_dedup(p)) {
+        prune_path(path, dry_run, &mut report).map_err(|e| e.to_string())?;
+    }
+    Ok(report)
+}
+
+fn is_dedup(path: &ResolvedBackupPath) -> bool {
+    path.backup_method
+        .as_ref()
+        .is_some_and(|methods| methods.iter().any(|m| m.eq_ignore_ascii_case("dedup")))
+}
+
+/// True if `retention` names at least one positive keep rule. Retention is
+/// optional in the config, so a path that never configured one resolves to
+/// `Retention::default()` (every rule `None`) -- without this guard that
+/// would bucket to an empty keep set and `prune_path` would delete every
+/// backup the first time someone ran `bacman prune` without `--dry-run`.
+fn has_retention_rule(retention: &Retention) -> bool {
+    [
+        retention.keep_last,
+        retention.keep_daily,
+        retention.keep_weekly,
+        retention.keep_monthly,
+    ]
+    .iter()
+    .any(|rule| rule.is_some_and(|count| count > 0))
+}
+
+fn prune_path(
+    path: &ResolvedBackupPath,
+    dry_run: bool,
+    report: &mut PruneReport,
+) -> std::io::Result<()> {
+    if !has_retention_rule(&path.retention) {
+        // No retention configured for this path: keep everything rather
+        // than treating "nothing configured" as "keep nothing".
+        return Ok(());
+    }
+
+    let root = store::store_root_for(&path.path);
+    let backups = store::list_backups(&root)?; // newest first
+    let keep: HashSet<i64> = backups_to_keep(&backups, &path.retention)
+        .into_iter()
+        .collect();
+
+    let to_remove: Vec<&Backup> = backups.iter().filter(|b| !keep.contains(&b.timestamp)).collect();
+    if to_remove.is_empty() {
+        return Ok(());
+    }
+
+    let surviving: Vec<&Backup> = backups.iter().filter(|b| keep.contains(&b.timestamp)).collect();
+    let referenced: HashSet<&str> = surviving
+        .iter()
+        .flat_map(|b| b.manifest.files.iter())
+        .flat_map(|f| f.chunks.iter())
+        .map(|h| h.as_str())
+        .collect();
+
+    for backup in &to_remove {
+        report.removed.push(PrunedBackup {
+            path: path.path.clone(),
+            timestamp: backup.timestamp,
+        });
+    }
+
+    if dry_run {
+        return Ok(());
+    }
+
+    for backup in &to_remove {
+        store::delete_backup(&root, backup.timestamp)?;
+    }
+
+    // A chunk is only safe to delete once no surviving backup references
+    // it, even if it was introduced by a backup we just removed.
+    let encrypt_repo = (path.encrypt == Some(true)).then(|| crypto::repo_id(path));
+    let mut chunk_store = ChunkStore::open(&root, encrypt_repo)?;
+    let removed_hashes: HashSet<&str> = to_remove
+        .iter()
+        .flat_map(|b| b.manifest.files.iter())
+        .flat_map(|f| f.chunks.iter())
+        .map(|h| h.as_str())
+        .collect();
+
+    for hash in removed_hashes {
+        if !referenced.contains(hash) && chunk_store.delete(hash).is_ok() {
+            report.chunks_freed += 1;
+        }
+    }
+
+    Ok(())
+}
+
+/// Standard keep-daily/weekly/monthly bucketing: `backups` must already be
+/// sorted newest-to-oldest. The most recent backup, `keep_last` backups,
+/// and the newest backup per distinct day/week/month bucket (up to each
+/// rule's count) all survive.
+fn backups_to_keep(backups: &[Backup], retention: &Retention) -> Vec<i64> {
+    let mut keep = Vec::new();
+
+    let keep_last = retention.keep_last.unwrap_or(0);
+    for backup in backups.iter().take(keep_last) {
+        keep.push(backup.timestamp);
+    }
+
+    keep.extend(bucketed_keep(backups, retention.keep_daily.unwrap_or(0), day_bucket));
+    keep.extend(bucketed_keep(backups, retention.keep_weekly.unwrap_or(0), week_bucket));
+    keep.extend(bucketed_keep(backups, retention.keep_monthly.unwrap_or(0), month_bucket));
+
+    keep
+}
+
+/// Walks `backups` (newest first) keeping the first backup seen in each
+/// distinct bucket, until `count` distinct buckets have been kept.
+fn bucketed_keep(backups: &[Backup], count: usize, bucket_of: fn(i64) -> i64) -> Vec<i64> {
+    if count == 0 {
+        return Vec::new();
+    }
+
+    let mut seen_buckets = HashSet::new();
+    let mut keep = Vec::new();
+    for backup in backups {
+        let bucket = bucket_of(backup.timestamp);
+        if seen_buckets.insert(bucket) {
+            keep.push(backup.timestamp);
+            if seen_buckets.len() >= count {
+                break;
+            }
+        }
+    }
+    keep
+}
+
+const SECS_PER_DAY: i64 = 86_400;
+
+fn day_bucket(timestamp: i64) -> i64 {
+    timestamp.div_euclid(SECS_PER_DAY)
+}
+
+fn week_bucket(timestamp: i64) -> i64 {
+    timestamp.div_euclid(SECS_PER_DAY * 7)
+}
+
+fn month_bucket(timestamp: i64) -> i64 {
+    // A fixed 30-day bucket is close enough for retention purposes and
+    // avoids pulling in a calendar-aware date library.
+    timestamp.div_euclid(SECS_PER_DAY * 30)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backup_at(timestamp: i64) -> Backup {
+        Backup {
+            timestamp,
+            manifest: Default::default(),
+        }
+    }
+
+    #[test]
+    fn no_retention_configured_keeps_everything() {
+        assert!(!has_retention_rule(&Retention::default()));
+    }
+
+    #[test]
+    fn a_positive_rule_is_detected() {
+        let retention = Retention {
+            keep_last: Some(3),
+            ..Default::default()
+        };
+        assert!(has_retention_rule(&retention));
+    }
+
+    #[test]
+    fn keep_last_keeps_the_newest_n_backups() {
+        // Newest first, as `list_backups` returns them.
+        let backups: Vec<Backup> = (0..5).rev().map(|i| backup_at(i * SECS_PER_DAY)).collect();
+
+        let retention = Retention {
+            keep_last: Some(2),
+            ..Default::default()
+        };
+        let keep = backups_to_keep(&backups, &retention);
+
+        assert_eq!(keep.len(), 2);
+        assert!(keep.contains(&backups[0].timestamp));
+        assert!(keep.contains(&backups[1].timestamp));
+    }
+
+    #[test]
+    fn keep_daily_keeps_one_backup_per_distinct_day() {
+        // Two backups on day 0, two on day 1, newest first.
+        let backups = vec![
+            backup_at(SECS_PER_DAY + 3600),
+            backup_at(SECS_PER_DAY),
+            backup_at(3600),
+            backup_at(0),
+        ];
+
+        let retention = Retention {
+            keep_daily: Some(2),
+            ..Default::default()
+        };
+        let keep = backups_to_keep(&backups, &retention);
+
+        assert_eq!(keep.len(), 2);
+        assert!(keep.contains(&(SECS_PER_DAY + 3600))); // newest of day 1
+        assert!(keep.contains(&3600)); // newest of day 0
+    }
+
+    #[test]
+    fn keep_weekly_and_monthly_bucket_over_longer_spans() {
+        let one_week_ago = 7 * SECS_PER_DAY;
+        let one_month_ago = 30 * SECS_PER_DAY;
+        // Newest first, as `list_backups` returns them.
+        let backups = vec![backup_at(0), backup_at(-one_week_ago), backup_at(-one_month_ago)];
+
+        let weekly = Retention {
+            keep_weekly: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(backups_to_keep(&backups, &weekly).len(), 3);
+
+        let monthly = Retention {
+            keep_monthly: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(backups_to_keep(&backups, &monthly), vec![0]);
+    }
+
+    #[test]
+    fn zero_count_rules_keep_nothing_from_that_rule() {
+        let backups = vec![backup_at(0), backup_at(SECS_PER_DAY)];
+        assert!(bucketed_keep(&backups, 0, day_bucket).is_empty());
+    }
+}
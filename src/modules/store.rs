@@ -0,0 +1,278 @@
+//! Content-addressed chunk storage for the `dedup` backup method. Chunks
+//! are keyed by their BLAKE3 hash so identical content written by different
+//! files, or by the same file across backups, is only ever written once.
+
+use crate::modules::chunker::{self, Chunk};
+use crate::modules::config;
+use crate::modules::crypto;
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Per-file mtime/size fingerprint plus its ordered chunk hash list, so an
+/// incremental run can skip re-chunking files that have not changed and a
+/// restore can reassemble the file by concatenating chunks in order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileManifest {
+    pub path: String,
+    pub mtime: i64,
+    pub size: u64,
+    pub chunks: Vec<String>,
+}
+
+/// A snapshot of every file backed up for one resolved path. Used both to
+/// restore and as the "reference backup" that the next interval run diffs
+/// against to decide which files need re-chunking.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub files: Vec<FileManifest>,
+}
+
+impl BackupManifest {
+    pub fn file(&self, path: &str) -> Option<&FileManifest> {
+        self.files.iter().find(|f| f.path == path)
+    }
+}
+
+/// Directory holding one resolved path's chunk store and manifest, rooted
+/// under the platform data directory so it survives alongside the config.
+pub fn store_root_for(path: &str) -> PathBuf {
+    let data_dir = ProjectDirs::from("", "", "bacman")
+        .map(|dirs| dirs.data_dir().to_path_buf())
+        .unwrap_or_else(|| PathBuf::from("."));
+    let sanitized = path.replace(['/', '\\'], "_");
+    data_dir.join("store").join(sanitized)
+}
+
+/// One completed backup run for a resolved path: its manifest plus the time
+/// it finished. Kept as one file per run (rather than overwriting a single
+/// "latest" file) so retention/prune has a full history to bucket over.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Backup {
+    pub timestamp: i64,
+    pub manifest: BackupManifest,
+}
+
+fn backups_dir(root: &Path) -> PathBuf {
+    root.join("backups")
+}
+
+/// Persists `backup` as a new backup run for the store at `root`.
+pub fn save_backup(root: &Path, backup: &Backup) -> io::Result<()> {
+    let dir = backups_dir(root);
+    fs::create_dir_all(&dir)?;
+    let json = serde_json::to_string_pretty(backup)?;
+    fs::write(dir.join(format!("{}.json", backup.timestamp)), json)
+}
+
+/// Deletes the backup run recorded at `timestamp`.
+pub fn delete_backup(root: &Path, timestamp: i64) -> io::Result<()> {
+    fs::remove_file(backups_dir(root).join(format!("{}.json", timestamp)))
+}
+
+/// Lists every backup run recorded at `root`, newest first.
+pub fn list_backups(root: &Path) -> io::Result<Vec<Backup>> {
+    let dir = backups_dir(root);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        if entry.path().extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let json = fs::read_to_string(entry.path())?;
+        backups.push(serde_json::from_str(&json)?);
+    }
+    backups.sort_by_key(|b: &Backup| std::cmp::Reverse(b.timestamp));
+    Ok(backups)
+}
+
+/// The most recently completed backup run at `root`, if any, used both as
+/// the rescan reference and as the state a restore reads from.
+pub fn latest_backup(root: &Path) -> io::Result<Option<Backup>> {
+    Ok(list_backups(root)?.into_iter().next())
+}
+
+/// A content-addressed directory of chunks, backed by an in-memory index of
+/// hash -> on-disk location built from the directory listing on open.
+///
+/// Chunk hashes are always computed over plaintext, so content-defined
+/// deduplication keeps working even when `encrypt_repo` is set -- only the
+/// bytes written to and read from disk are encrypted.
+pub struct ChunkStore {
+    root: PathBuf,
+    index: HashMap<String, PathBuf>,
+    encrypt_repo: Option<String>,
+}
+
+impl ChunkStore {
+    pub fn open(root: impl Into<PathBuf>, encrypt_repo: Option<String>) -> io::Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        let mut index = HashMap::new();
+        for entry in fs::read_dir(&root)? {
+            let entry = entry?;
+            // Skip the `backups/` subdirectory and anything else that
+            // isn't a chunk file written by `put`.
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            if let Some(hash) = entry.file_name().to_str() {
+                index.insert(hash.to_string(), entry.path());
+            }
+        }
+        Ok(Self { root, index, encrypt_repo })
+    }
+
+    fn chunk_path(&self, hash: &str) -> PathBuf {
+        self.root.join(hash)
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.index.contains_key(hash)
+    }
+
+    /// Writes `chunk` to the store unless its hash is already present.
+    pub fn put(&mut self, chunk: &Chunk) -> io::Result<()> {
+        if self.contains(&chunk.hash) {
+            return Ok(());
+        }
+        let path = self.chunk_path(&chunk.hash);
+        let bytes = self.maybe_encrypt(&chunk.data)?;
+        fs::write(&path, &bytes)?;
+        self.index.insert(chunk.hash.clone(), path);
+        Ok(())
+    }
+
+    pub fn get(&self, hash: &str) -> io::Result<Vec<u8>> {
+        let path = self.index.get(hash).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("unknown chunk {}", hash))
+        })?;
+        let bytes = fs::read(path)?;
+        self.maybe_decrypt(&bytes)
+    }
+
+    /// Removes a chunk no longer referenced by any surviving backup.
+    pub fn delete(&mut self, hash: &str) -> io::Result<()> {
+        let path = self.index.remove(hash).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("unknown chunk {}", hash))
+        })?;
+        fs::remove_file(path)
+    }
+
+    fn maybe_encrypt(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match &self.encrypt_repo {
+            Some(repo) => crypto::encrypt(repo, data).map_err(to_io_error),
+            None => Ok(data.to_vec()),
+        }
+    }
+
+    fn maybe_decrypt(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match &self.encrypt_repo {
+            Some(repo) => crypto::decrypt(repo, data).map_err(to_io_error),
+            None => Ok(data.to_vec()),
+        }
+    }
+}
+
+fn to_io_error(e: crypto::CryptoError) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+fn mtime_secs(metadata: &fs::Metadata) -> i64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Chunks `path` and writes any unseen chunks into `store`, returning the
+/// file's manifest entry.
+pub fn backup_file(store: &mut ChunkStore, path: &Path) -> io::Result<FileManifest> {
+    let data = fs::read(path)?;
+    let metadata = fs::metadata(path)?;
+    let chunks = chunker::chunk(&data);
+
+    let mut hashes = Vec::with_capacity(chunks.len());
+    for chunk in &chunks {
+        store.put(chunk)?;
+        hashes.push(chunk.hash.clone());
+    }
+
+    Ok(FileManifest {
+        path: path.display().to_string(),
+        mtime: mtime_secs(&metadata),
+        size: metadata.len(),
+        chunks: hashes,
+    })
+}
+
+/// Reassembles a file by concatenating its chunks in order and writing the
+/// result to `dest`.
+pub fn restore_file(store: &ChunkStore, manifest: &FileManifest, dest: &Path) -> io::Result<()> {
+    let mut out = Vec::with_capacity(manifest.size as usize);
+    for hash in &manifest.chunks {
+        out.extend(store.get(hash)?);
+    }
+    fs::write(dest, out)
+}
+
+/// Restores every file in `target_path`'s latest backup, either back to its
+/// original location or, when `dest_dir` is given, alongside the original
+/// path joined underneath it. Returns the number of files restored.
+pub fn restore_path(target_path: &str, dest_dir: Option<&str>) -> Result<usize, String> {
+    let resolved = config::deserialize_config()?;
+    let path = resolved
+        .iter()
+        .find(|p| p.path == target_path)
+        .ok_or_else(|| format!("No configured backup path matches: {}", target_path))?;
+
+    let root = store_root_for(&path.path);
+    let backup = latest_backup(&root)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("No backup found for path: {}", path.path))?;
+
+    let encrypt_repo = (path.encrypt == Some(true)).then(|| crypto::repo_id(path));
+    let chunk_store = ChunkStore::open(&root, encrypt_repo).map_err(|e| e.to_string())?;
+
+    for file in &backup.manifest.files {
+        let dest = match dest_dir {
+            Some(dir) => Path::new(dir).join(
+                Path::new(&file.path)
+                    .components()
+                    .filter(|c| matches!(c, std::path::Component::Normal(_)))
+                    .collect::<PathBuf>(),
+            ),
+            None => PathBuf::from(&file.path),
+        };
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        restore_file(&chunk_store, file, &dest).map_err(|e| e.to_string())?;
+    }
+
+    Ok(backup.manifest.files.len())
+}
+
+/// True if `path` needs re-chunking: no reference entry exists for it yet,
+/// or its mtime/size differ from the one recorded in `reference`.
+pub fn needs_rescan(path: &Path, reference: Option<&BackupManifest>) -> io::Result<bool> {
+    let Some(reference) = reference else {
+        return Ok(true);
+    };
+    let Some(entry) = reference.file(&path.display().to_string()) else {
+        return Ok(true);
+    };
+
+    let metadata = fs::metadata(path)?;
+    Ok(mtime_secs(&metadata) != entry.mtime || metadata.len() != entry.size)
+}
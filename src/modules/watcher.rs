@@ -0,0 +1,305 @@
+use crate::modules::config::{build_exclude_set, ResolvedBackupPath};
+use crate::modules::crypto;
+use crate::modules::store::{self, BackupManifest, ChunkStore};
+use globset::GlobSet;
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A `dedup`-method path's open chunk store plus the manifest the watcher
+/// keeps up to date as files change, so each change only has to re-chunk
+/// the one file that changed rather than the whole tree.
+struct DedupState {
+    root: PathBuf,
+    store: ChunkStore,
+    manifest: BackupManifest,
+}
+
+fn is_dedup(path: &ResolvedBackupPath) -> bool {
+    path.backup_method
+        .as_ref()
+        .is_some_and(|methods| methods.iter().any(|m| m.eq_ignore_ascii_case("dedup")))
+}
+
+/// Opens the chunk store and loads the most recent manifest for every
+/// `dedup`-method path, so the watcher can do incremental backups against
+/// the same reference `bacman check`/`bacman prune` already operate on.
+fn build_dedup_state(paths: &[ResolvedBackupPath]) -> HashMap<String, DedupState> {
+    let mut state = HashMap::new();
+    for path in paths {
+        if !is_dedup(path) {
+            continue;
+        }
+
+        let root = store::store_root_for(&path.path);
+        let encrypt_repo = (path.encrypt == Some(true)).then(|| crypto::repo_id(path));
+        match ChunkStore::open(&root, encrypt_repo) {
+            Ok(chunk_store) => {
+                let manifest = store::latest_backup(&root)
+                    .ok()
+                    .flatten()
+                    .map(|b| b.manifest)
+                    .unwrap_or_default();
+                state.insert(
+                    path.path.clone(),
+                    DedupState {
+                        root,
+                        store: chunk_store,
+                        manifest,
+                    },
+                );
+            }
+            Err(e) => eprintln!("Failed to open chunk store for {}: {}", path.path, e),
+        }
+    }
+    state
+}
+
+/// Starts a recursive filesystem watcher for every resolved backup path and
+/// queues a backup whenever a non-excluded file changes under it.
+pub fn watcher(paths: Vec<ResolvedBackupPath>) {
+    let (tx, rx) = channel();
+    let mut fs_watcher: RecommendedWatcher = Watcher::new(tx, notify::Config::default())
+        .expect("Failed to create filesystem watcher");
+
+    // One GlobSet per watched root, built once so each event only costs a
+    // single `is_match` lookup.
+    let exclude_sets: HashMap<String, GlobSet> = paths
+        .iter()
+        .map(|p| (p.path.clone(), build_exclude_set(&p.exclude)))
+        .collect();
+
+    let mut dedup_state = build_dedup_state(&paths);
+
+    // `same_device` roots are watched directory-by-directory rather than
+    // with `RecursiveMode::Recursive`, so unlike a plain recursive watch
+    // they don't automatically pick up directories created after startup.
+    // Remembering each root's `st_dev` here lets `handle_event` register a
+    // watch for a new directory as soon as it appears.
+    let mut same_device_roots: HashMap<PathBuf, u64> = HashMap::new();
+    for path in &paths {
+        if let Some(dev) = register_watch(&mut fs_watcher, path) {
+            same_device_roots.insert(PathBuf::from(&path.path), dev);
+        }
+    }
+
+    loop {
+        match rx.recv() {
+            Ok(Ok(event)) => handle_event(
+                event,
+                &exclude_sets,
+                &mut fs_watcher,
+                &same_device_roots,
+                &mut dedup_state,
+            ),
+            Ok(Err(e)) => eprintln!("Watch error: {}", e),
+            Err(e) => eprintln!("Watch channel closed: {}", e),
+        }
+    }
+}
+
+/// Registers a watch for `path`, honoring `same_device` by walking the tree
+/// ourselves and only adding non-recursive watches for directories that
+/// stay on the root's filesystem (skipping network shares, `/proc`-style
+/// pseudo filesystems, and other mounts). Returns the root's `st_dev` when
+/// `same_device` watching was used, so the caller can recognize new
+/// directories created under it later.
+fn register_watch(fs_watcher: &mut RecommendedWatcher, path: &ResolvedBackupPath) -> Option<u64> {
+    if !path.same_device {
+        if let Err(e) = fs_watcher.watch(Path::new(&path.path), RecursiveMode::Recursive) {
+            eprintln!("Failed to watch {}: {}", path.path, e);
+        }
+        return None;
+    }
+
+    #[cfg(unix)]
+    {
+        same_device::watch_same_device(fs_watcher, Path::new(&path.path))
+    }
+
+    #[cfg(not(unix))]
+    {
+        // `same_device` has no meaning without `st_dev`; fall back to a
+        // plain recursive watch elsewhere.
+        if let Err(e) = fs_watcher.watch(Path::new(&path.path), RecursiveMode::Recursive) {
+            eprintln!("Failed to watch {}: {}", path.path, e);
+        }
+        None
+    }
+}
+
+#[cfg(unix)]
+mod same_device {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::MetadataExt;
+
+    /// Walks `root` and adds a non-recursive watch for every directory that
+    /// sits on the same filesystem as `root` itself, stopping descent as
+    /// soon as a directory's `st_dev` differs. Returns `root`'s `st_dev` on
+    /// success, so the caller can recognize new directories created under
+    /// it after the initial walk.
+    pub fn watch_same_device(fs_watcher: &mut RecommendedWatcher, root: &Path) -> Option<u64> {
+        let root_dev = match fs::metadata(root) {
+            Ok(meta) => meta.dev(),
+            Err(e) => {
+                eprintln!("Failed to stat {}: {}", root.display(), e);
+                return None;
+            }
+        };
+        walk(fs_watcher, root, root_dev);
+        Some(root_dev)
+    }
+
+    /// Registers a non-recursive watch for `dir`, a directory that just
+    /// appeared under a `same_device` root, and recurses into any of its
+    /// own subdirectories that are already present -- mirroring the initial
+    /// walk so a directory created with pre-existing children isn't missed.
+    pub fn watch_new_dir(fs_watcher: &mut RecommendedWatcher, dir: &Path, root_dev: u64) {
+        walk(fs_watcher, dir, root_dev);
+    }
+
+    fn walk(fs_watcher: &mut RecommendedWatcher, dir: &Path, root_dev: u64) {
+        match fs::metadata(dir) {
+            Ok(meta) if meta.dev() == root_dev => (),
+            Ok(_) => return, // different filesystem: do not descend
+            Err(e) => {
+                eprintln!("Failed to stat {}: {}", dir.display(), e);
+                return;
+            }
+        }
+
+        if let Err(e) = fs_watcher.watch(dir, RecursiveMode::NonRecursive) {
+            eprintln!("Failed to watch {}: {}", dir.display(), e);
+        }
+
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", dir.display(), e);
+                return;
+            }
+        };
+
+        for entry in entries.flatten() {
+            // `file_type()` (unlike `Path::is_dir`) doesn't follow symlinks,
+            // so a symlink to a directory elsewhere -- possibly outside any
+            // configured backup path, or cyclic -- is never descended into.
+            let is_real_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+            if is_real_dir {
+                walk(fs_watcher, &entry.path(), root_dev);
+            }
+        }
+    }
+}
+
+fn handle_event(
+    event: Event,
+    exclude_sets: &HashMap<String, GlobSet>,
+    fs_watcher: &mut RecommendedWatcher,
+    same_device_roots: &HashMap<PathBuf, u64>,
+    dedup_state: &mut HashMap<String, DedupState>,
+) {
+    #[cfg(unix)]
+    if matches!(event.kind, notify::EventKind::Create(_)) {
+        for created in &event.paths {
+            // `symlink_metadata` (unlike `Path::is_dir`) doesn't follow
+            // symlinks, so a symlink-to-directory dropped into a watched
+            // tree is never walked into.
+            let is_real_dir = std::fs::symlink_metadata(created)
+                .map(|m| m.is_dir())
+                .unwrap_or(false);
+            if is_real_dir {
+                watch_if_new_same_device_dir(fs_watcher, created, same_device_roots);
+            }
+        }
+    }
+
+    for changed in event.paths {
+        let root = exclude_sets.keys().find(|root| changed.starts_with(root.as_str()));
+        let Some(root) = root else { continue };
+
+        if exclude_sets[root].is_match(&changed) {
+            continue;
+        }
+
+        if !changed.is_file() {
+            continue;
+        }
+
+        queue_backup(&changed, root, dedup_state);
+    }
+}
+
+/// If `dir` was created under one of `same_device_roots`, registers a watch
+/// for it (and any of its own pre-existing subdirectories) so later changes
+/// inside it are seen -- the per-directory `same_device` watches set up at
+/// startup only cover directories that already existed then.
+#[cfg(unix)]
+fn watch_if_new_same_device_dir(
+    fs_watcher: &mut RecommendedWatcher,
+    dir: &Path,
+    same_device_roots: &HashMap<PathBuf, u64>,
+) {
+    // When configured roots nest (e.g. a separate mount configured as its
+    // own same_device root underneath another), prefer the most specific
+    // (longest path) match so the new directory is validated against the
+    // filesystem it actually sits on.
+    let root_dev = same_device_roots
+        .iter()
+        .filter(|(root, _)| dir.starts_with(root.as_path()))
+        .max_by_key(|(root, _)| root.as_os_str().len())
+        .map(|(_, dev)| *dev);
+
+    if let Some(root_dev) = root_dev {
+        same_device::watch_new_dir(fs_watcher, dir, root_dev);
+    }
+}
+
+/// Performs an incremental `dedup` backup of `path` when its root has an
+/// open chunk store, re-chunking only when `needs_rescan` says its mtime/size
+/// changed since the last recorded manifest entry. Non-`dedup` roots (for
+/// the other `backup_method`s this repo's config accepts but doesn't yet
+/// implement) just log the change, as before.
+fn queue_backup(path: &Path, root: &str, dedup_state: &mut HashMap<String, DedupState>) {
+    let Some(state) = dedup_state.get_mut(root) else {
+        println!("queued backup: {}", path.display());
+        return;
+    };
+
+    match store::needs_rescan(path, Some(&state.manifest)) {
+        Ok(false) => return,
+        Ok(true) => {}
+        Err(e) => {
+            eprintln!("Failed to stat {}: {}", path.display(), e);
+            return;
+        }
+    }
+
+    let file_manifest = match store::backup_file(&mut state.store, path) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            eprintln!("Failed to back up {}: {}", path.display(), e);
+            return;
+        }
+    };
+
+    state.manifest.files.retain(|f| f.path != file_manifest.path);
+    state.manifest.files.push(file_manifest);
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+
+    let backup = store::Backup {
+        timestamp,
+        manifest: state.manifest.clone(),
+    };
+
+    if let Err(e) = store::save_backup(&state.root, &backup) {
+        eprintln!("Failed to save backup for {}: {}", root, e);
+    }
+}